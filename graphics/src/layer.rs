@@ -0,0 +1,45 @@
+//! Organize rendering primitives into a flat structure that can be easily
+//! processed by renderers.
+use crate::triangle;
+use iced_core::{Point, Rectangle};
+
+/// A mesh of triangles.
+#[derive(Debug, Clone, Copy)]
+pub struct Mesh<'a> {
+    /// The origin of the vertices of the [`Mesh`].
+    pub origin: Point,
+
+    /// The vertex and index buffers of the [`Mesh`].
+    pub buffers: &'a triangle::Mesh2D,
+
+    /// The clipping bounds of the [`Mesh`].
+    pub clip_bounds: Rectangle,
+
+    /// The depth to render the [`Mesh`] at, letting meshes be submitted in
+    /// any order while still occluding each other correctly when the
+    /// renderer uses a depth buffer.
+    pub z: f32,
+
+    /// An optional color transform applied to the [`Mesh`].
+    pub color_transform: Option<ColorTransform>,
+}
+
+/// A per-mesh color transform, applied as `clamp(color * mult + add, 0, 1)`.
+/// This enables cheap tints, fades and highlights on solid-color meshes
+/// without re-tessellating or rebuilding vertex buffers.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ColorTransform {
+    /// The factor each color component is multiplied by.
+    pub mult: [f32; 4],
+    /// The value added to each color component after the multiply.
+    pub add: [f32; 4],
+}
+
+impl Default for ColorTransform {
+    fn default() -> Self {
+        Self {
+            mult: [1.0; 4],
+            add: [0.0; 4],
+        }
+    }
+}
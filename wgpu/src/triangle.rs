@@ -1,9 +1,11 @@
 //! Draw meshes of triangles.
 use crate::{settings, Transformation};
 use iced_graphics::layer;
+use std::collections::HashMap;
 use std::mem;
 use zerocopy::AsBytes;
 
+pub use iced_graphics::layer::ColorTransform;
 pub use iced_graphics::triangle::{Mesh2D, Vertex2D};
 
 mod msaa;
@@ -11,16 +13,227 @@ mod msaa;
 const UNIFORM_BUFFER_SIZE: usize = 50;
 const VERTEX_BUFFER_SIZE: usize = 10_000;
 const INDEX_BUFFER_SIZE: usize = 10_000;
+const GRADIENT_MAX_STOPS: usize = 16;
+const INSTANCE_BUFFER_SIZE: usize = 1_000;
+const DEPTH_FORMAT: wgpu::TextureFormat = wgpu::TextureFormat::Depth32Float;
+
+/// A vertex for a mesh whose fill is computed from a [`Gradient`] rather
+/// than stored per-vertex. Only the position is needed; the gradient
+/// coordinate is derived from it in the vertex shader.
+#[repr(C)]
+#[derive(Debug, Clone, Copy, PartialEq, bytemuck::Pod, bytemuck::Zeroable)]
+pub struct GradientVertex2D {
+    /// The vertex position in the local coordinate system of a mesh.
+    pub position: [f32; 2],
+}
+
+/// The kind of gradient interpolation to perform.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum GradientKind {
+    /// Interpolate along the `x` axis of the gradient space.
+    Linear,
+    /// Interpolate along the distance from the origin of the gradient space.
+    Radial,
+}
+
+/// The behavior of a [`Gradient`] outside of its `0..1` stop range.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Spread {
+    /// Clamp to the closest stop.
+    Pad,
+    /// Mirror the gradient back and forth.
+    Reflect,
+    /// Repeat the gradient from the start.
+    Repeat,
+}
+
+/// A linear or radial gradient fill for a [`Mesh2D`].
+#[derive(Debug, Clone)]
+pub struct Gradient {
+    /// The kind of gradient to render.
+    pub kind: GradientKind,
+    /// The color stops of the gradient, as `(ratio, color)` pairs in
+    /// ascending order. Capped at [`GRADIENT_MAX_STOPS`].
+    pub stops: Vec<(f32, [f32; 4])>,
+    /// The transform mapping mesh-space coordinates into gradient space.
+    pub transform: Transformation,
+    /// How the gradient repeats outside of its stop range.
+    pub spread: Spread,
+}
+
+/// A mesh of triangles whose fill is described by a [`Gradient`] instead of
+/// per-vertex colors.
+#[derive(Debug, Clone)]
+pub struct GradientMesh<'a> {
+    /// The vertices and indices of the mesh.
+    pub buffers: &'a GradientMesh2D,
+    /// The origin of the mesh, relative to the layer it belongs to.
+    pub origin: iced_graphics::Point,
+    /// The clip bounds of the mesh.
+    pub clip_bounds: iced_graphics::Rectangle,
+    /// The gradient the mesh is filled with.
+    pub gradient: &'a Gradient,
+}
+
+/// The vertex and index buffers of a [`GradientMesh`].
+#[derive(Debug, Clone)]
+pub struct GradientMesh2D {
+    /// The vertices of the mesh.
+    pub vertices: Vec<GradientVertex2D>,
+    /// The indices describing the triangles of the mesh.
+    pub indices: Vec<u32>,
+}
+
+/// A vertex for a mesh that is filled by sampling a texture rather than
+/// an interpolated vertex color.
+#[repr(C)]
+#[derive(Debug, Clone, Copy, PartialEq, bytemuck::Pod, bytemuck::Zeroable)]
+pub struct TexturedVertex2D {
+    /// The vertex position in the local coordinate system of a mesh.
+    pub position: [f32; 2],
+    /// The texture coordinate of the vertex.
+    pub uv: [f32; 2],
+}
+
+/// An opaque handle identifying a texture previously uploaded to the GPU,
+/// used to key the per-texture bind groups of the textured mesh pipeline.
+pub type TextureId = u64;
+
+/// A mesh of triangles filled with a texture, optionally tinted.
+#[derive(Debug, Clone)]
+pub struct TexturedMesh<'a> {
+    /// The vertices and indices of the mesh.
+    pub buffers: &'a TexturedMesh2D,
+    /// The origin of the mesh, relative to the layer it belongs to.
+    pub origin: iced_graphics::Point,
+    /// The clip bounds of the mesh.
+    pub clip_bounds: iced_graphics::Rectangle,
+    /// The texture the mesh samples from.
+    pub texture: TextureId,
+    /// A per-mesh tint multiplied with the sampled texture color.
+    pub tint: [f32; 4],
+}
+
+/// The vertex and index buffers of a [`TexturedMesh`].
+#[derive(Debug, Clone)]
+pub struct TexturedMesh2D {
+    /// The vertices of the mesh.
+    pub vertices: Vec<TexturedVertex2D>,
+    /// The indices describing the triangles of the mesh.
+    pub indices: Vec<u32>,
+}
+
+/// A per-instance transform uploaded as a vertex attribute for
+/// [`Pipeline::draw_instanced`]. It is stored as four column vectors (to
+/// match the column-major layout `Uniforms::transform` already uses) so it
+/// can be bound as a regular vertex buffer with
+/// `wgpu::InputStepMode::Instance` at shader locations `2..=5`.
+#[repr(C)]
+#[derive(Debug, Clone, Copy, PartialEq, bytemuck::Pod, bytemuck::Zeroable)]
+pub struct InstanceTransform {
+    columns: [[f32; 4]; 4],
+}
+
+impl From<Transformation> for InstanceTransform {
+    fn from(transformation: Transformation) -> Self {
+        let matrix: [f32; 16] = transformation.into();
+
+        Self {
+            columns: [
+                [matrix[0], matrix[1], matrix[2], matrix[3]],
+                [matrix[4], matrix[5], matrix[6], matrix[7]],
+                [matrix[8], matrix[9], matrix[10], matrix[11]],
+                [matrix[12], matrix[13], matrix[14], matrix[15]],
+            ],
+        }
+    }
+}
+
+/// A depth attachment used by [`Pipeline::draw`] to let meshes be
+/// submitted in any order while still occluding correctly, recreated
+/// alongside the MSAA blit targets whenever the target is resized.
+#[derive(Debug)]
+struct DepthBuffer {
+    view: wgpu::TextureView,
+    width: u32,
+    height: u32,
+}
+
+impl DepthBuffer {
+    fn new(
+        device: &wgpu::Device,
+        width: u32,
+        height: u32,
+        sample_count: u32,
+    ) -> Self {
+        let texture = device.create_texture(&wgpu::TextureDescriptor {
+            label: Some("iced_wgpu::triangle depth buffer"),
+            size: wgpu::Extent3d {
+                width,
+                height,
+                depth: 1,
+            },
+            mip_level_count: 1,
+            sample_count,
+            dimension: wgpu::TextureDimension::D2,
+            format: DEPTH_FORMAT,
+            usage: wgpu::TextureUsage::OUTPUT_ATTACHMENT,
+        });
+
+        let view = texture.create_view(&wgpu::TextureViewDescriptor::default());
+
+        Self {
+            view,
+            width,
+            height,
+        }
+    }
+
+    fn ensure(
+        &mut self,
+        device: &wgpu::Device,
+        width: u32,
+        height: u32,
+        sample_count: u32,
+    ) {
+        if self.width != width || self.height != height {
+            *self = Self::new(device, width, height, sample_count);
+        }
+    }
+}
 
 #[derive(Debug)]
 pub(crate) struct Pipeline {
     pipeline: wgpu::RenderPipeline,
     blit: Option<msaa::Blit>,
+    sample_count: u32,
+    depth_buffer: DepthBuffer,
     constants_layout: wgpu::BindGroupLayout,
     constants: wgpu::BindGroup,
     uniforms_buffer: Buffer<Uniforms>,
     vertex_buffer: Buffer<Vertex2D>,
     index_buffer: Buffer<u32>,
+    instanced_pipeline: wgpu::RenderPipeline,
+    instanced_constants_layout: wgpu::BindGroupLayout,
+    instanced_constants: wgpu::BindGroup,
+    instanced_uniforms_buffer: Buffer<Uniforms>,
+    instanced_vertex_buffer: Buffer<Vertex2D>,
+    instanced_index_buffer: Buffer<u32>,
+    instance_buffer: Buffer<InstanceTransform>,
+    gradient_pipeline: wgpu::RenderPipeline,
+    gradient_constants_layout: wgpu::BindGroupLayout,
+    gradient_constants: wgpu::BindGroup,
+    gradient_uniforms_buffer: Buffer<GradientUniforms>,
+    gradient_vertex_buffer: Buffer<GradientVertex2D>,
+    gradient_index_buffer: Buffer<u32>,
+    textured_pipeline: wgpu::RenderPipeline,
+    textured_constants_layout: wgpu::BindGroupLayout,
+    textured_uniforms_buffer: Buffer<TexturedUniforms>,
+    textured_vertex_buffer: Buffer<TexturedVertex2D>,
+    textured_index_buffer: Buffer<u32>,
+    // Rebuilt whenever `textured_uniforms_buffer` is resized, since every
+    // bind group references its underlying `wgpu::Buffer` at binding 0.
+    textured_bind_groups: HashMap<TextureId, wgpu::BindGroup>,
 }
 
 #[derive(Debug)]
@@ -70,18 +283,35 @@ impl<T> Buffer<T> {
     }
 }
 
+/// A single mesh's draw metadata, kept around after upload so the render
+/// pass can group and merge draw calls without re-reading `layer::Mesh`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct Draw {
+    clip_bounds: (u32, u32, u32, u32),
+    origin: (f32, f32),
+    z: f32,
+    color_transform: Option<ColorTransform>,
+    index_offset: u64,
+    index_count: usize,
+    uniform: usize,
+}
+
 impl Pipeline {
     pub fn new(
         device: &wgpu::Device,
         format: wgpu::TextureFormat,
         antialiasing: Option<settings::Antialiasing>,
     ) -> Pipeline {
+        let sample_count =
+            u32::from(antialiasing.map(|a| a.sample_count()).unwrap_or(1));
+
         let constants_layout =
             device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
                 label: None,
                 entries: &[wgpu::BindGroupLayoutEntry {
                     binding: 0,
-                    visibility: wgpu::ShaderStage::VERTEX,
+                    visibility: wgpu::ShaderStage::VERTEX
+                        | wgpu::ShaderStage::FRAGMENT,
                     ty: wgpu::BindingType::UniformBuffer {
                         dynamic: true,
                         min_binding_size: wgpu::BufferSize::new(
@@ -159,7 +389,12 @@ impl Pipeline {
                     },
                     write_mask: wgpu::ColorWrite::ALL,
                 }],
-                depth_stencil_state: None,
+                depth_stencil_state: Some(wgpu::DepthStencilStateDescriptor {
+                    format: DEPTH_FORMAT,
+                    depth_write_enabled: true,
+                    depth_compare: wgpu::CompareFunction::LessEqual,
+                    stencil: wgpu::StencilStateDescriptor::default(),
+                }),
                 vertex_state: wgpu::VertexStateDescriptor {
                     index_format: wgpu::IndexFormat::Uint32,
                     vertex_buffers: &[wgpu::VertexBufferDescriptor {
@@ -181,149 +416,1000 @@ impl Pipeline {
                         ],
                     }],
                 },
-                sample_count: u32::from(
-                    antialiasing.map(|a| a.sample_count()).unwrap_or(1),
-                ),
+                sample_count,
                 sample_mask: !0,
                 alpha_to_coverage_enabled: false,
             });
 
-        Pipeline {
-            pipeline,
-            blit: antialiasing.map(|a| msaa::Blit::new(device, format, a)),
-            constants_layout,
-            constants: constant_bind_group,
-            uniforms_buffer: constants_buffer,
-            vertex_buffer: Buffer::new(
-                device,
-                VERTEX_BUFFER_SIZE,
-                wgpu::BufferUsage::VERTEX | wgpu::BufferUsage::COPY_DST,
-            ),
-            index_buffer: Buffer::new(
-                device,
-                INDEX_BUFFER_SIZE,
-                wgpu::BufferUsage::INDEX | wgpu::BufferUsage::COPY_DST,
-            ),
-        }
-    }
+        let depth_buffer = DepthBuffer::new(device, 1, 1, sample_count);
 
-    pub fn draw(
-        &mut self,
-        device: &wgpu::Device,
-        staging_belt: &mut wgpu::util::StagingBelt,
-        encoder: &mut wgpu::CommandEncoder,
-        target: &wgpu::TextureView,
-        target_width: u32,
-        target_height: u32,
-        transformation: Transformation,
-        scale_factor: f32,
-        meshes: &[layer::Mesh<'_>],
-    ) {
-        // This looks a bit crazy, but we are just counting how many vertices
-        // and indices we will need to handle.
-        // TODO: Improve readability
-        let (total_vertices, total_indices) = meshes
-            .iter()
-            .map(|layer::Mesh { buffers, .. }| {
-                (buffers.vertices.len(), buffers.indices.len())
-            })
-            .fold((0, 0), |(total_v, total_i), (v, i)| {
-                (total_v + v, total_i + i)
+        let gradient_constants_layout =
+            device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+                label: None,
+                entries: &[wgpu::BindGroupLayoutEntry {
+                    binding: 0,
+                    visibility: wgpu::ShaderStage::VERTEX
+                        | wgpu::ShaderStage::FRAGMENT,
+                    ty: wgpu::BindingType::UniformBuffer {
+                        dynamic: true,
+                        min_binding_size: wgpu::BufferSize::new(
+                            mem::size_of::<GradientUniforms>() as u64,
+                        ),
+                    },
+                    count: None,
+                }],
             });
 
-        // Then we ensure the current buffers are big enough, resizing if
-        // necessary
-        let _ = self.vertex_buffer.expand(device, total_vertices);
-        let _ = self.index_buffer.expand(device, total_indices);
+        let gradient_constants_buffer = Buffer::new(
+            device,
+            UNIFORM_BUFFER_SIZE,
+            wgpu::BufferUsage::UNIFORM | wgpu::BufferUsage::COPY_DST,
+        );
 
-        // If the uniforms buffer is resized, then we need to recreate its
-        // bind group.
-        if self.uniforms_buffer.expand(device, meshes.len()) {
-            self.constants =
-                device.create_bind_group(&wgpu::BindGroupDescriptor {
-                    label: None,
-                    layout: &self.constants_layout,
-                    entries: &[wgpu::BindGroupEntry {
-                        binding: 0,
-                        resource: wgpu::BindingResource::Buffer(
-                            self.uniforms_buffer.raw.slice(
-                                0..std::mem::size_of::<Uniforms>() as u64,
-                            ),
+        let gradient_constant_bind_group =
+            device.create_bind_group(&wgpu::BindGroupDescriptor {
+                label: None,
+                layout: &gradient_constants_layout,
+                entries: &[wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: wgpu::BindingResource::Buffer(
+                        gradient_constants_buffer.raw.slice(
+                            0..std::mem::size_of::<GradientUniforms>() as u64,
                         ),
-                    }],
-                });
-        }
-
-        let mut uniforms: Vec<Uniforms> = Vec::with_capacity(meshes.len());
-        let mut offsets: Vec<(
-            wgpu::BufferAddress,
-            wgpu::BufferAddress,
-            usize,
-        )> = Vec::with_capacity(meshes.len());
-        let mut last_vertex = 0;
-        let mut last_index = 0;
+                    ),
+                }],
+            });
 
-        // We upload everything upfront
-        for mesh in meshes {
-            let transform = (transformation
-                * Transformation::translate(mesh.origin.x, mesh.origin.y))
-            .into();
+        let gradient_layout =
+            device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+                label: None,
+                push_constant_ranges: &[],
+                bind_group_layouts: &[&gradient_constants_layout],
+            });
 
-            let vertices = bytemuck::cast_slice(&mesh.buffers.vertices);
-            let indices = bytemuck::cast_slice(&mesh.buffers.indices);
+        let gradient_vs_module = device.create_shader_module(
+            wgpu::include_spirv!("shader/gradient.vert.spv"),
+        );
 
-            if let Some(vertices_size) =
-                wgpu::BufferSize::new(vertices.len() as u64)
-            {
-                if let Some(indices_size) =
-                    wgpu::BufferSize::new(indices.len() as u64)
-                {
-                    {
-                        let mut vertex_buffer = staging_belt.write_buffer(
-                            encoder,
-                            &self.vertex_buffer.raw,
-                            (std::mem::size_of::<Vertex2D>() * last_vertex)
-                                as u64,
-                            vertices_size,
-                            device,
-                        );
+        let gradient_fs_module = device.create_shader_module(
+            wgpu::include_spirv!("shader/gradient.frag.spv"),
+        );
 
-                        vertex_buffer.copy_from_slice(vertices);
-                    }
+        let gradient_pipeline =
+            device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+                label: None,
+                layout: Some(&gradient_layout),
+                vertex_stage: wgpu::ProgrammableStageDescriptor {
+                    module: &gradient_vs_module,
+                    entry_point: "main",
+                },
+                fragment_stage: Some(wgpu::ProgrammableStageDescriptor {
+                    module: &gradient_fs_module,
+                    entry_point: "main",
+                }),
+                rasterization_state: Some(wgpu::RasterizationStateDescriptor {
+                    front_face: wgpu::FrontFace::Cw,
+                    cull_mode: wgpu::CullMode::None,
+                    ..Default::default()
+                }),
+                primitive_topology: wgpu::PrimitiveTopology::TriangleList,
+                color_states: &[wgpu::ColorStateDescriptor {
+                    format,
+                    color_blend: wgpu::BlendDescriptor {
+                        src_factor: wgpu::BlendFactor::SrcAlpha,
+                        dst_factor: wgpu::BlendFactor::OneMinusSrcAlpha,
+                        operation: wgpu::BlendOperation::Add,
+                    },
+                    alpha_blend: wgpu::BlendDescriptor {
+                        src_factor: wgpu::BlendFactor::One,
+                        dst_factor: wgpu::BlendFactor::OneMinusSrcAlpha,
+                        operation: wgpu::BlendOperation::Add,
+                    },
+                    write_mask: wgpu::ColorWrite::ALL,
+                }],
+                depth_stencil_state: None,
+                vertex_state: wgpu::VertexStateDescriptor {
+                    index_format: wgpu::IndexFormat::Uint32,
+                    vertex_buffers: &[wgpu::VertexBufferDescriptor {
+                        stride: mem::size_of::<GradientVertex2D>() as u64,
+                        step_mode: wgpu::InputStepMode::Vertex,
+                        attributes: &[wgpu::VertexAttributeDescriptor {
+                            shader_location: 0,
+                            format: wgpu::VertexFormat::Float2,
+                            offset: 0,
+                        }],
+                    }],
+                },
+                sample_count,
+                sample_mask: !0,
+                alpha_to_coverage_enabled: false,
+            });
 
-                    {
-                        let mut index_buffer = staging_belt.write_buffer(
-                            encoder,
-                            &self.index_buffer.raw,
-                            (std::mem::size_of::<u32>() * last_index) as u64,
-                            indices_size,
-                            device,
-                        );
+        let textured_constants_layout =
+            device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+                label: None,
+                entries: &[
+                    wgpu::BindGroupLayoutEntry {
+                        binding: 0,
+                        visibility: wgpu::ShaderStage::VERTEX
+                            | wgpu::ShaderStage::FRAGMENT,
+                        ty: wgpu::BindingType::UniformBuffer {
+                            dynamic: true,
+                            min_binding_size: wgpu::BufferSize::new(
+                                mem::size_of::<TexturedUniforms>() as u64,
+                            ),
+                        },
+                        count: None,
+                    },
+                    wgpu::BindGroupLayoutEntry {
+                        binding: 1,
+                        visibility: wgpu::ShaderStage::FRAGMENT,
+                        ty: wgpu::BindingType::SampledTexture {
+                            dimension: wgpu::TextureViewDimension::D2,
+                            component_type: wgpu::TextureComponentType::Float,
+                            multisampled: false,
+                        },
+                        count: None,
+                    },
+                    wgpu::BindGroupLayoutEntry {
+                        binding: 2,
+                        visibility: wgpu::ShaderStage::FRAGMENT,
+                        ty: wgpu::BindingType::Sampler { comparison: false },
+                        count: None,
+                    },
+                ],
+            });
 
-                        index_buffer.copy_from_slice(indices);
-                    }
+        let textured_uniforms_buffer = Buffer::new(
+            device,
+            UNIFORM_BUFFER_SIZE,
+            wgpu::BufferUsage::UNIFORM | wgpu::BufferUsage::COPY_DST,
+        );
 
-                    uniforms.push(transform);
-                    offsets.push((
-                        last_vertex as u64,
-                        last_index as u64,
-                        mesh.buffers.indices.len(),
-                    ));
+        let textured_layout =
+            device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+                label: None,
+                push_constant_ranges: &[],
+                bind_group_layouts: &[&textured_constants_layout],
+            });
 
-                    last_vertex += mesh.buffers.vertices.len();
-                    last_index += mesh.buffers.indices.len();
-                }
-            }
-        }
+        let textured_vs_module = device.create_shader_module(
+            wgpu::include_spirv!("shader/textured.vert.spv"),
+        );
 
-        let uniforms = uniforms.as_bytes();
+        let textured_fs_module = device.create_shader_module(
+            wgpu::include_spirv!("shader/textured.frag.spv"),
+        );
 
-        if let Some(uniforms_size) =
-            wgpu::BufferSize::new(uniforms.len() as u64)
-        {
+        let textured_pipeline =
+            device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+                label: None,
+                layout: Some(&textured_layout),
+                vertex_stage: wgpu::ProgrammableStageDescriptor {
+                    module: &textured_vs_module,
+                    entry_point: "main",
+                },
+                fragment_stage: Some(wgpu::ProgrammableStageDescriptor {
+                    module: &textured_fs_module,
+                    entry_point: "main",
+                }),
+                rasterization_state: Some(wgpu::RasterizationStateDescriptor {
+                    front_face: wgpu::FrontFace::Cw,
+                    cull_mode: wgpu::CullMode::None,
+                    ..Default::default()
+                }),
+                primitive_topology: wgpu::PrimitiveTopology::TriangleList,
+                color_states: &[wgpu::ColorStateDescriptor {
+                    format,
+                    color_blend: wgpu::BlendDescriptor {
+                        src_factor: wgpu::BlendFactor::SrcAlpha,
+                        dst_factor: wgpu::BlendFactor::OneMinusSrcAlpha,
+                        operation: wgpu::BlendOperation::Add,
+                    },
+                    alpha_blend: wgpu::BlendDescriptor {
+                        src_factor: wgpu::BlendFactor::One,
+                        dst_factor: wgpu::BlendFactor::OneMinusSrcAlpha,
+                        operation: wgpu::BlendOperation::Add,
+                    },
+                    write_mask: wgpu::ColorWrite::ALL,
+                }],
+                depth_stencil_state: None,
+                vertex_state: wgpu::VertexStateDescriptor {
+                    index_format: wgpu::IndexFormat::Uint32,
+                    vertex_buffers: &[wgpu::VertexBufferDescriptor {
+                        stride: mem::size_of::<TexturedVertex2D>() as u64,
+                        step_mode: wgpu::InputStepMode::Vertex,
+                        attributes: &[
+                            // Position
+                            wgpu::VertexAttributeDescriptor {
+                                shader_location: 0,
+                                format: wgpu::VertexFormat::Float2,
+                                offset: 0,
+                            },
+                            // UV
+                            wgpu::VertexAttributeDescriptor {
+                                shader_location: 1,
+                                format: wgpu::VertexFormat::Float2,
+                                offset: 4 * 2,
+                            },
+                        ],
+                    }],
+                },
+                sample_count,
+                sample_mask: !0,
+                alpha_to_coverage_enabled: false,
+            });
+
+        let instanced_constants_layout =
+            device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+                label: None,
+                entries: &[wgpu::BindGroupLayoutEntry {
+                    binding: 0,
+                    visibility: wgpu::ShaderStage::VERTEX,
+                    ty: wgpu::BindingType::UniformBuffer {
+                        dynamic: true,
+                        min_binding_size: wgpu::BufferSize::new(
+                            mem::size_of::<Uniforms>() as u64,
+                        ),
+                    },
+                    count: None,
+                }],
+            });
+
+        let instanced_constants_buffer = Buffer::new(
+            device,
+            UNIFORM_BUFFER_SIZE,
+            wgpu::BufferUsage::UNIFORM | wgpu::BufferUsage::COPY_DST,
+        );
+
+        let instanced_constant_bind_group =
+            device.create_bind_group(&wgpu::BindGroupDescriptor {
+                label: None,
+                layout: &instanced_constants_layout,
+                entries: &[wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: wgpu::BindingResource::Buffer(
+                        instanced_constants_buffer
+                            .raw
+                            .slice(0..std::mem::size_of::<Uniforms>() as u64),
+                    ),
+                }],
+            });
+
+        let instanced_layout =
+            device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+                label: None,
+                push_constant_ranges: &[],
+                bind_group_layouts: &[&instanced_constants_layout],
+            });
+
+        let instanced_vs_module = device.create_shader_module(
+            wgpu::include_spirv!("shader/instanced.vert.spv"),
+        );
+
+        let instanced_fs_module = device.create_shader_module(
+            wgpu::include_spirv!("shader/instanced.frag.spv"),
+        );
+
+        let instanced_pipeline =
+            device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+                label: None,
+                layout: Some(&instanced_layout),
+                vertex_stage: wgpu::ProgrammableStageDescriptor {
+                    module: &instanced_vs_module,
+                    entry_point: "main",
+                },
+                fragment_stage: Some(wgpu::ProgrammableStageDescriptor {
+                    module: &instanced_fs_module,
+                    entry_point: "main",
+                }),
+                rasterization_state: Some(wgpu::RasterizationStateDescriptor {
+                    front_face: wgpu::FrontFace::Cw,
+                    cull_mode: wgpu::CullMode::None,
+                    ..Default::default()
+                }),
+                primitive_topology: wgpu::PrimitiveTopology::TriangleList,
+                color_states: &[wgpu::ColorStateDescriptor {
+                    format,
+                    color_blend: wgpu::BlendDescriptor {
+                        src_factor: wgpu::BlendFactor::SrcAlpha,
+                        dst_factor: wgpu::BlendFactor::OneMinusSrcAlpha,
+                        operation: wgpu::BlendOperation::Add,
+                    },
+                    alpha_blend: wgpu::BlendDescriptor {
+                        src_factor: wgpu::BlendFactor::One,
+                        dst_factor: wgpu::BlendFactor::OneMinusSrcAlpha,
+                        operation: wgpu::BlendOperation::Add,
+                    },
+                    write_mask: wgpu::ColorWrite::ALL,
+                }],
+                depth_stencil_state: None,
+                vertex_state: wgpu::VertexStateDescriptor {
+                    index_format: wgpu::IndexFormat::Uint32,
+                    vertex_buffers: &[
+                        wgpu::VertexBufferDescriptor {
+                            stride: mem::size_of::<Vertex2D>() as u64,
+                            step_mode: wgpu::InputStepMode::Vertex,
+                            attributes: &[
+                                // Position
+                                wgpu::VertexAttributeDescriptor {
+                                    shader_location: 0,
+                                    format: wgpu::VertexFormat::Float2,
+                                    offset: 0,
+                                },
+                                // Color
+                                wgpu::VertexAttributeDescriptor {
+                                    shader_location: 1,
+                                    format: wgpu::VertexFormat::Float4,
+                                    offset: 4 * 2,
+                                },
+                            ],
+                        },
+                        wgpu::VertexBufferDescriptor {
+                            stride: mem::size_of::<InstanceTransform>() as u64,
+                            step_mode: wgpu::InputStepMode::Instance,
+                            attributes: &[
+                                // Transform column 0
+                                wgpu::VertexAttributeDescriptor {
+                                    shader_location: 2,
+                                    format: wgpu::VertexFormat::Float4,
+                                    offset: 0,
+                                },
+                                // Transform column 1
+                                wgpu::VertexAttributeDescriptor {
+                                    shader_location: 3,
+                                    format: wgpu::VertexFormat::Float4,
+                                    offset: 4 * 4,
+                                },
+                                // Transform column 2
+                                wgpu::VertexAttributeDescriptor {
+                                    shader_location: 4,
+                                    format: wgpu::VertexFormat::Float4,
+                                    offset: 4 * 4 * 2,
+                                },
+                                // Transform column 3
+                                wgpu::VertexAttributeDescriptor {
+                                    shader_location: 5,
+                                    format: wgpu::VertexFormat::Float4,
+                                    offset: 4 * 4 * 3,
+                                },
+                            ],
+                        },
+                    ],
+                },
+                sample_count,
+                sample_mask: !0,
+                alpha_to_coverage_enabled: false,
+            });
+
+        Pipeline {
+            pipeline,
+            blit: antialiasing.map(|a| msaa::Blit::new(device, format, a)),
+            sample_count,
+            depth_buffer,
+            constants_layout,
+            constants: constant_bind_group,
+            uniforms_buffer: constants_buffer,
+            vertex_buffer: Buffer::new(
+                device,
+                VERTEX_BUFFER_SIZE,
+                wgpu::BufferUsage::VERTEX | wgpu::BufferUsage::COPY_DST,
+            ),
+            index_buffer: Buffer::new(
+                device,
+                INDEX_BUFFER_SIZE,
+                wgpu::BufferUsage::INDEX | wgpu::BufferUsage::COPY_DST,
+            ),
+            instanced_pipeline,
+            instanced_constants_layout,
+            instanced_constants: instanced_constant_bind_group,
+            instanced_uniforms_buffer: instanced_constants_buffer,
+            instanced_vertex_buffer: Buffer::new(
+                device,
+                VERTEX_BUFFER_SIZE,
+                wgpu::BufferUsage::VERTEX | wgpu::BufferUsage::COPY_DST,
+            ),
+            instanced_index_buffer: Buffer::new(
+                device,
+                INDEX_BUFFER_SIZE,
+                wgpu::BufferUsage::INDEX | wgpu::BufferUsage::COPY_DST,
+            ),
+            instance_buffer: Buffer::new(
+                device,
+                INSTANCE_BUFFER_SIZE,
+                wgpu::BufferUsage::VERTEX | wgpu::BufferUsage::COPY_DST,
+            ),
+            gradient_pipeline,
+            gradient_constants_layout,
+            gradient_constants: gradient_constant_bind_group,
+            gradient_uniforms_buffer: gradient_constants_buffer,
+            gradient_vertex_buffer: Buffer::new(
+                device,
+                VERTEX_BUFFER_SIZE,
+                wgpu::BufferUsage::VERTEX | wgpu::BufferUsage::COPY_DST,
+            ),
+            gradient_index_buffer: Buffer::new(
+                device,
+                INDEX_BUFFER_SIZE,
+                wgpu::BufferUsage::INDEX | wgpu::BufferUsage::COPY_DST,
+            ),
+            textured_pipeline,
+            textured_constants_layout,
+            textured_uniforms_buffer,
+            textured_vertex_buffer: Buffer::new(
+                device,
+                VERTEX_BUFFER_SIZE,
+                wgpu::BufferUsage::VERTEX | wgpu::BufferUsage::COPY_DST,
+            ),
+            textured_index_buffer: Buffer::new(
+                device,
+                INDEX_BUFFER_SIZE,
+                wgpu::BufferUsage::INDEX | wgpu::BufferUsage::COPY_DST,
+            ),
+            textured_bind_groups: HashMap::new(),
+        }
+    }
+
+    pub fn draw(
+        &mut self,
+        device: &wgpu::Device,
+        staging_belt: &mut wgpu::util::StagingBelt,
+        encoder: &mut wgpu::CommandEncoder,
+        target: &wgpu::TextureView,
+        target_width: u32,
+        target_height: u32,
+        transformation: Transformation,
+        scale_factor: f32,
+        meshes: &[layer::Mesh<'_>],
+    ) {
+        // This looks a bit crazy, but we are just counting how many vertices
+        // and indices we will need to handle.
+        // TODO: Improve readability
+        let (total_vertices, total_indices) = meshes
+            .iter()
+            .map(|layer::Mesh { buffers, .. }| {
+                (buffers.vertices.len(), buffers.indices.len())
+            })
+            .fold((0, 0), |(total_v, total_i), (v, i)| {
+                (total_v + v, total_i + i)
+            });
+
+        // Then we ensure the current buffers are big enough, resizing if
+        // necessary
+        let _ = self.vertex_buffer.expand(device, total_vertices);
+        let _ = self.index_buffer.expand(device, total_indices);
+
+        // If the uniforms buffer is resized, then we need to recreate its
+        // bind group.
+        if self.uniforms_buffer.expand(device, meshes.len()) {
+            self.constants =
+                device.create_bind_group(&wgpu::BindGroupDescriptor {
+                    label: None,
+                    layout: &self.constants_layout,
+                    entries: &[wgpu::BindGroupEntry {
+                        binding: 0,
+                        resource: wgpu::BindingResource::Buffer(
+                            self.uniforms_buffer.raw.slice(
+                                0..std::mem::size_of::<Uniforms>() as u64,
+                            ),
+                        ),
+                    }],
+                });
+        }
+
+        let mut uniforms: Vec<Uniforms> = Vec::with_capacity(meshes.len());
+
+        // One entry per mesh, in submission order. We bake the vertex
+        // offset directly into the uploaded indices (instead of relying on
+        // `draw_indexed`'s `base_vertex`), so that any run of meshes can be
+        // collapsed into a single draw call below, regardless of where each
+        // one's vertices happen to live in the shared buffer.
+        let mut draws: Vec<Draw> = Vec::with_capacity(meshes.len());
+        let mut last_vertex = 0;
+        let mut last_index = 0;
+
+        // We upload everything upfront
+        for mesh in meshes {
+            let transform = transformation
+                * Transformation::translate(mesh.origin.x, mesh.origin.y);
+
+            let mesh_uniforms = Uniforms::new(
+                transform,
+                mesh.z,
+                mesh.color_transform.unwrap_or_default(),
+            );
+
+            let vertices = bytemuck::cast_slice(&mesh.buffers.vertices);
+            let offset_indices: Vec<u32> = mesh
+                .buffers
+                .indices
+                .iter()
+                .map(|index| index + last_vertex as u32)
+                .collect();
+            let indices = bytemuck::cast_slice(&offset_indices);
+
+            if let Some(vertices_size) =
+                wgpu::BufferSize::new(vertices.len() as u64)
+            {
+                if let Some(indices_size) =
+                    wgpu::BufferSize::new(indices.len() as u64)
+                {
+                    {
+                        let mut vertex_buffer = staging_belt.write_buffer(
+                            encoder,
+                            &self.vertex_buffer.raw,
+                            (std::mem::size_of::<Vertex2D>() * last_vertex)
+                                as u64,
+                            vertices_size,
+                            device,
+                        );
+
+                        vertex_buffer.copy_from_slice(vertices);
+                    }
+
+                    {
+                        let mut index_buffer = staging_belt.write_buffer(
+                            encoder,
+                            &self.index_buffer.raw,
+                            (std::mem::size_of::<u32>() * last_index) as u64,
+                            indices_size,
+                            device,
+                        );
+
+                        index_buffer.copy_from_slice(indices);
+                    }
+
+                    let clip_bounds = (mesh.clip_bounds * scale_factor).snap();
+
+                    uniforms.push(mesh_uniforms);
+                    draws.push(Draw {
+                        clip_bounds: (
+                            clip_bounds.x,
+                            clip_bounds.y,
+                            clip_bounds.width,
+                            clip_bounds.height,
+                        ),
+                        origin: (mesh.origin.x, mesh.origin.y),
+                        z: mesh.z,
+                        color_transform: mesh.color_transform,
+                        index_offset: last_index as u64,
+                        index_count: mesh.buffers.indices.len(),
+                        uniform: uniforms.len() - 1,
+                    });
+
+                    last_vertex += mesh.buffers.vertices.len();
+                    last_index += mesh.buffers.indices.len();
+                }
+            }
+        }
+
+        let uniforms = uniforms.as_bytes();
+
+        if let Some(uniforms_size) =
+            wgpu::BufferSize::new(uniforms.len() as u64)
+        {
+            let mut uniforms_buffer = staging_belt.write_buffer(
+                encoder,
+                &self.uniforms_buffer.raw,
+                0,
+                uniforms_size,
+                device,
+            );
+
+            uniforms_buffer.copy_from_slice(uniforms);
+        }
+
+        self.depth_buffer.ensure(
+            device,
+            target_width,
+            target_height,
+            self.sample_count,
+        );
+
+        {
+            let (attachment, resolve_target, load) =
+                if let Some(blit) = &mut self.blit {
+                    let (attachment, resolve_target) =
+                        blit.targets(device, target_width, target_height);
+
+                    (
+                        attachment,
+                        Some(resolve_target),
+                        wgpu::LoadOp::Clear(wgpu::Color {
+                            r: 0.0,
+                            g: 0.0,
+                            b: 0.0,
+                            a: 0.0,
+                        }),
+                    )
+                } else {
+                    (target, None, wgpu::LoadOp::Load)
+                };
+
+            let mut render_pass =
+                encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+                    color_attachments: &[
+                        wgpu::RenderPassColorAttachmentDescriptor {
+                            attachment,
+                            resolve_target,
+                            ops: wgpu::Operations { load, store: true },
+                        },
+                    ],
+                    depth_stencil_attachment: Some(
+                        wgpu::RenderPassDepthStencilAttachmentDescriptor {
+                            attachment: &self.depth_buffer.view,
+                            depth_ops: Some(wgpu::Operations {
+                                load: wgpu::LoadOp::Clear(1.0),
+                                store: true,
+                            }),
+                            stencil_ops: None,
+                        },
+                    ),
+                });
+
+            render_pass.set_pipeline(&self.pipeline);
+
+            // The index and vertex buffers never change across meshes within
+            // this draw call, so we only need to bind them once.
+            render_pass.set_index_buffer(self.index_buffer.raw.slice(..));
+            render_pass.set_vertex_buffer(0, self.vertex_buffer.raw.slice(..));
+
+            // Consecutive meshes sharing a scissor rect form a "run"; we set
+            // the scissor once per run instead of once per mesh. Meshes
+            // within a run that also share an origin, a depth and a color
+            // transform (and so the same uniforms) are drawn with a single
+            // `draw_indexed` call, since their indices already sit in one
+            // contiguous range of the shared index buffer.
+            //
+            // We only group meshes that are *already* adjacent in
+            // submission order, rather than sorting `draws` by scissor
+            // rect: the depth test (`LessEqual`) lets meshes at equal depth
+            // both pass, and overlapping alpha-blended meshes composite
+            // differently in another order, so submission order still
+            // decides the result here, as it does in `draw_textured`. UIs
+            // that interleave two clip regions won't get the full
+            // draw-call reduction this could otherwise provide.
+            let mut runs = draws.as_slice();
+
+            while let Some(first) = runs.first() {
+                let clip_bounds = first.clip_bounds;
+                let run_len = runs
+                    .iter()
+                    .take_while(|draw| draw.clip_bounds == clip_bounds)
+                    .count();
+                let (run, rest) = runs.split_at(run_len);
+                runs = rest;
+
+                let (x, y, width, height) = clip_bounds;
+                render_pass.set_scissor_rect(x, y, width, height);
+
+                let mut batches = run;
+
+                while let Some(first) = batches.first() {
+                    let batch_len = batches
+                        .iter()
+                        .take_while(|draw| {
+                            draw.origin == first.origin
+                                && draw.z == first.z
+                                && draw.color_transform == first.color_transform
+                        })
+                        .count();
+                    let (batch, rest) = batches.split_at(batch_len);
+                    batches = rest;
+
+                    let index_count: usize =
+                        batch.iter().map(|draw| draw.index_count).sum();
+
+                    render_pass.set_bind_group(
+                        0,
+                        &self.constants,
+                        &[(std::mem::size_of::<Uniforms>() * first.uniform)
+                            as u32],
+                    );
+
+                    render_pass.draw_indexed(
+                        first.index_offset as u32
+                            ..(first.index_offset as usize + index_count)
+                                as u32,
+                        0,
+                        0..1,
+                    );
+                }
+            }
+        }
+
+        if let Some(blit) = &mut self.blit {
+            blit.draw(encoder, target);
+        }
+    }
+
+    /// Draws many instances of a single [`Mesh2D`] in one `draw_indexed`
+    /// call, uploading `instances` into a second, per-instance vertex
+    /// buffer instead of issuing a draw call per transform.
+    pub fn draw_instanced(
+        &mut self,
+        device: &wgpu::Device,
+        staging_belt: &mut wgpu::util::StagingBelt,
+        encoder: &mut wgpu::CommandEncoder,
+        target: &wgpu::TextureView,
+        target_width: u32,
+        target_height: u32,
+        transformation: Transformation,
+        scale_factor: f32,
+        mesh: &Mesh2D,
+        clip_bounds: iced_graphics::Rectangle,
+        instances: &[Transformation],
+    ) {
+        if instances.is_empty() {
+            return;
+        }
+
+        let _ = self
+            .instanced_vertex_buffer
+            .expand(device, mesh.vertices.len());
+        let _ = self
+            .instanced_index_buffer
+            .expand(device, mesh.indices.len());
+        let _ = self.instance_buffer.expand(device, instances.len());
+
+        if self.instanced_uniforms_buffer.expand(device, 1) {
+            self.instanced_constants =
+                device.create_bind_group(&wgpu::BindGroupDescriptor {
+                    label: None,
+                    layout: &self.instanced_constants_layout,
+                    entries: &[wgpu::BindGroupEntry {
+                        binding: 0,
+                        resource: wgpu::BindingResource::Buffer(
+                            self.instanced_uniforms_buffer.raw.slice(
+                                0..std::mem::size_of::<Uniforms>() as u64,
+                            ),
+                        ),
+                    }],
+                });
+        }
+
+        let vertices = bytemuck::cast_slice(&mesh.vertices);
+        let indices = bytemuck::cast_slice(&mesh.indices);
+
+        if let Some(vertices_size) =
+            wgpu::BufferSize::new(vertices.len() as u64)
+        {
+            let mut vertex_buffer = staging_belt.write_buffer(
+                encoder,
+                &self.instanced_vertex_buffer.raw,
+                0,
+                vertices_size,
+                device,
+            );
+
+            vertex_buffer.copy_from_slice(vertices);
+        }
+
+        if let Some(indices_size) = wgpu::BufferSize::new(indices.len() as u64)
+        {
+            let mut index_buffer = staging_belt.write_buffer(
+                encoder,
+                &self.instanced_index_buffer.raw,
+                0,
+                indices_size,
+                device,
+            );
+
+            index_buffer.copy_from_slice(indices);
+        }
+
+        let transforms: Vec<InstanceTransform> = instances
+            .iter()
+            .copied()
+            .map(InstanceTransform::from)
+            .collect();
+        let transforms = bytemuck::cast_slice(&transforms);
+
+        if let Some(instances_size) =
+            wgpu::BufferSize::new(transforms.len() as u64)
+        {
+            let mut instance_buffer = staging_belt.write_buffer(
+                encoder,
+                &self.instance_buffer.raw,
+                0,
+                instances_size,
+                device,
+            );
+
+            instance_buffer.copy_from_slice(transforms);
+        }
+
+        let uniforms = [Uniforms::from(transformation)];
+        let uniforms = uniforms.as_bytes();
+
+        if let Some(uniforms_size) =
+            wgpu::BufferSize::new(uniforms.len() as u64)
+        {
+            let mut uniforms_buffer = staging_belt.write_buffer(
+                encoder,
+                &self.instanced_uniforms_buffer.raw,
+                0,
+                uniforms_size,
+                device,
+            );
+
+            uniforms_buffer.copy_from_slice(uniforms);
+        }
+
+        {
+            let (attachment, resolve_target, load) =
+                if let Some(blit) = &mut self.blit {
+                    let (attachment, resolve_target) =
+                        blit.targets(device, target_width, target_height);
+
+                    (attachment, Some(resolve_target), wgpu::LoadOp::Load)
+                } else {
+                    (target, None, wgpu::LoadOp::Load)
+                };
+
+            let mut render_pass =
+                encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+                    color_attachments: &[
+                        wgpu::RenderPassColorAttachmentDescriptor {
+                            attachment,
+                            resolve_target,
+                            ops: wgpu::Operations { load, store: true },
+                        },
+                    ],
+                    depth_stencil_attachment: None,
+                });
+
+            let clip_bounds = (clip_bounds * scale_factor).snap();
+
+            render_pass.set_pipeline(&self.instanced_pipeline);
+
+            render_pass.set_scissor_rect(
+                clip_bounds.x,
+                clip_bounds.y,
+                clip_bounds.width,
+                clip_bounds.height,
+            );
+
+            render_pass.set_bind_group(0, &self.instanced_constants, &[0]);
+
+            render_pass
+                .set_index_buffer(self.instanced_index_buffer.raw.slice(..));
+
+            render_pass.set_vertex_buffer(
+                0,
+                self.instanced_vertex_buffer.raw.slice(..),
+            );
+
+            render_pass
+                .set_vertex_buffer(1, self.instance_buffer.raw.slice(..));
+
+            render_pass.draw_indexed(
+                0..mesh.indices.len() as u32,
+                0,
+                0..instances.len() as u32,
+            );
+        }
+
+        if let Some(blit) = &mut self.blit {
+            blit.draw(encoder, target);
+        }
+    }
+
+    /// Draws the given gradient-filled meshes, following the same
+    /// upload-then-draw shape as [`Pipeline::draw`] but targeting the
+    /// gradient pipeline and its own vertex/index/uniform buffers.
+    pub fn draw_gradient(
+        &mut self,
+        device: &wgpu::Device,
+        staging_belt: &mut wgpu::util::StagingBelt,
+        encoder: &mut wgpu::CommandEncoder,
+        target: &wgpu::TextureView,
+        target_width: u32,
+        target_height: u32,
+        transformation: Transformation,
+        scale_factor: f32,
+        meshes: &[GradientMesh<'_>],
+    ) {
+        let (total_vertices, total_indices) = meshes
+            .iter()
+            .map(|mesh| {
+                (mesh.buffers.vertices.len(), mesh.buffers.indices.len())
+            })
+            .fold((0, 0), |(total_v, total_i), (v, i)| {
+                (total_v + v, total_i + i)
+            });
+
+        let _ = self.gradient_vertex_buffer.expand(device, total_vertices);
+        let _ = self.gradient_index_buffer.expand(device, total_indices);
+
+        if self.gradient_uniforms_buffer.expand(device, meshes.len()) {
+            self.gradient_constants =
+                device.create_bind_group(&wgpu::BindGroupDescriptor {
+                    label: None,
+                    layout: &self.gradient_constants_layout,
+                    entries: &[wgpu::BindGroupEntry {
+                        binding: 0,
+                        resource: wgpu::BindingResource::Buffer(
+                            self.gradient_uniforms_buffer.raw.slice(
+                                0..std::mem::size_of::<GradientUniforms>()
+                                    as u64,
+                            ),
+                        ),
+                    }],
+                });
+        }
+
+        let mut uniforms: Vec<GradientUniforms> =
+            Vec::with_capacity(meshes.len());
+        let mut offsets: Vec<(
+            wgpu::BufferAddress,
+            wgpu::BufferAddress,
+            usize,
+        )> = Vec::with_capacity(meshes.len());
+        let mut last_vertex = 0;
+        let mut last_index = 0;
+
+        for mesh in meshes {
+            let transform = transformation
+                * Transformation::translate(mesh.origin.x, mesh.origin.y);
+
+            let vertices = bytemuck::cast_slice(&mesh.buffers.vertices);
+            let indices = bytemuck::cast_slice(&mesh.buffers.indices);
+
+            if let Some(vertices_size) =
+                wgpu::BufferSize::new(vertices.len() as u64)
+            {
+                if let Some(indices_size) =
+                    wgpu::BufferSize::new(indices.len() as u64)
+                {
+                    {
+                        let mut vertex_buffer = staging_belt.write_buffer(
+                            encoder,
+                            &self.gradient_vertex_buffer.raw,
+                            (std::mem::size_of::<GradientVertex2D>()
+                                * last_vertex)
+                                as u64,
+                            vertices_size,
+                            device,
+                        );
+
+                        vertex_buffer.copy_from_slice(vertices);
+                    }
+
+                    {
+                        let mut index_buffer = staging_belt.write_buffer(
+                            encoder,
+                            &self.gradient_index_buffer.raw,
+                            (std::mem::size_of::<u32>() * last_index) as u64,
+                            indices_size,
+                            device,
+                        );
+
+                        index_buffer.copy_from_slice(indices);
+                    }
+
+                    uniforms
+                        .push(GradientUniforms::new(transform, mesh.gradient));
+                    offsets.push((
+                        last_vertex as u64,
+                        last_index as u64,
+                        mesh.buffers.indices.len(),
+                    ));
+
+                    last_vertex += mesh.buffers.vertices.len();
+                    last_index += mesh.buffers.indices.len();
+                }
+            }
+        }
+
+        let uniforms = uniforms.as_bytes();
+
+        if let Some(uniforms_size) =
+            wgpu::BufferSize::new(uniforms.len() as u64)
+        {
             let mut uniforms_buffer = staging_belt.write_buffer(
                 encoder,
-                &self.uniforms_buffer.raw,
+                &self.gradient_uniforms_buffer.raw,
                 0,
                 uniforms_size,
                 device,
@@ -338,16 +1424,7 @@ impl Pipeline {
                     let (attachment, resolve_target) =
                         blit.targets(device, target_width, target_height);
 
-                    (
-                        attachment,
-                        Some(resolve_target),
-                        wgpu::LoadOp::Clear(wgpu::Color {
-                            r: 0.0,
-                            g: 0.0,
-                            b: 0.0,
-                            a: 0.0,
-                        }),
-                    )
+                    (attachment, Some(resolve_target), wgpu::LoadOp::Load)
                 } else {
                     (target, None, wgpu::LoadOp::Load)
                 };
@@ -364,7 +1441,7 @@ impl Pipeline {
                     depth_stencil_attachment: None,
                 });
 
-            render_pass.set_pipeline(&self.pipeline);
+            render_pass.set_pipeline(&self.gradient_pipeline);
 
             for (i, (vertex_offset, index_offset, indices)) in
                 offsets.into_iter().enumerate()
@@ -380,14 +1457,254 @@ impl Pipeline {
 
                 render_pass.set_bind_group(
                     0,
-                    &self.constants,
-                    &[(std::mem::size_of::<Uniforms>() * i) as u32],
+                    &self.gradient_constants,
+                    &[(std::mem::size_of::<GradientUniforms>() * i) as u32],
                 );
 
-                render_pass.set_index_buffer(self.index_buffer.raw.slice(..));
-
                 render_pass
-                    .set_vertex_buffer(0, self.vertex_buffer.raw.slice(..));
+                    .set_index_buffer(self.gradient_index_buffer.raw.slice(..));
+
+                render_pass.set_vertex_buffer(
+                    0,
+                    self.gradient_vertex_buffer.raw.slice(..),
+                );
+
+                render_pass.draw_indexed(
+                    index_offset as u32
+                        ..(index_offset as usize + indices) as u32,
+                    vertex_offset as i32,
+                    0..1,
+                );
+            }
+        }
+
+        if let Some(blit) = &mut self.blit {
+            blit.draw(encoder, target);
+        }
+    }
+
+    /// Draws the given textured meshes in submission order.
+    ///
+    /// We don't group or sort meshes by texture handle before drawing:
+    /// this pipeline has no depth test, so submission order is the only
+    /// thing that keeps overlapping, alpha-blended textured meshes
+    /// compositing the way the caller intended. Rebinding the bind group
+    /// per mesh is unavoidable anyway, since its dynamic offset changes
+    /// per mesh regardless of texture.
+    pub fn draw_textured(
+        &mut self,
+        device: &wgpu::Device,
+        staging_belt: &mut wgpu::util::StagingBelt,
+        encoder: &mut wgpu::CommandEncoder,
+        target: &wgpu::TextureView,
+        target_width: u32,
+        target_height: u32,
+        transformation: Transformation,
+        scale_factor: f32,
+        textures: &HashMap<TextureId, (wgpu::TextureView, wgpu::Sampler)>,
+        meshes: &[TexturedMesh<'_>],
+    ) {
+        let (total_vertices, total_indices) = meshes
+            .iter()
+            .map(|mesh| {
+                (mesh.buffers.vertices.len(), mesh.buffers.indices.len())
+            })
+            .fold((0, 0), |(total_v, total_i), (v, i)| {
+                (total_v + v, total_i + i)
+            });
+
+        let _ = self.textured_vertex_buffer.expand(device, total_vertices);
+        let _ = self.textured_index_buffer.expand(device, total_indices);
+
+        if self.textured_uniforms_buffer.expand(device, meshes.len()) {
+            // The underlying buffer changed, so every bind group that
+            // referenced it at binding 0 is now stale.
+            self.textured_bind_groups.clear();
+        }
+
+        for texture in meshes.iter().map(|mesh| mesh.texture) {
+            if !self.textured_bind_groups.contains_key(&texture) {
+                if let Some((view, sampler)) = textures.get(&texture) {
+                    let bind_group = device.create_bind_group(
+                        &wgpu::BindGroupDescriptor {
+                            label: None,
+                            layout: &self.textured_constants_layout,
+                            entries: &[
+                                wgpu::BindGroupEntry {
+                                    binding: 0,
+                                    resource: wgpu::BindingResource::Buffer(
+                                        self.textured_uniforms_buffer
+                                            .raw
+                                            .slice(
+                                                0..std::mem::size_of::<
+                                                    TexturedUniforms,
+                                                >()
+                                                    as u64,
+                                            ),
+                                    ),
+                                },
+                                wgpu::BindGroupEntry {
+                                    binding: 1,
+                                    resource: wgpu::BindingResource::TextureView(
+                                        view,
+                                    ),
+                                },
+                                wgpu::BindGroupEntry {
+                                    binding: 2,
+                                    resource: wgpu::BindingResource::Sampler(
+                                        sampler,
+                                    ),
+                                },
+                            ],
+                        },
+                    );
+
+                    let _ =
+                        self.textured_bind_groups.insert(texture, bind_group);
+                }
+            }
+        }
+
+        let mut uniforms: Vec<TexturedUniforms> =
+            Vec::with_capacity(meshes.len());
+        let mut offsets: Vec<(
+            wgpu::BufferAddress,
+            wgpu::BufferAddress,
+            usize,
+            TextureId,
+        )> = Vec::with_capacity(meshes.len());
+        let mut last_vertex = 0;
+        let mut last_index = 0;
+
+        for mesh in meshes {
+            let transform = transformation
+                * Transformation::translate(mesh.origin.x, mesh.origin.y);
+
+            let vertices = bytemuck::cast_slice(&mesh.buffers.vertices);
+            let indices = bytemuck::cast_slice(&mesh.buffers.indices);
+
+            if let Some(vertices_size) =
+                wgpu::BufferSize::new(vertices.len() as u64)
+            {
+                if let Some(indices_size) =
+                    wgpu::BufferSize::new(indices.len() as u64)
+                {
+                    {
+                        let mut vertex_buffer = staging_belt.write_buffer(
+                            encoder,
+                            &self.textured_vertex_buffer.raw,
+                            (std::mem::size_of::<TexturedVertex2D>()
+                                * last_vertex)
+                                as u64,
+                            vertices_size,
+                            device,
+                        );
+
+                        vertex_buffer.copy_from_slice(vertices);
+                    }
+
+                    {
+                        let mut index_buffer = staging_belt.write_buffer(
+                            encoder,
+                            &self.textured_index_buffer.raw,
+                            (std::mem::size_of::<u32>() * last_index) as u64,
+                            indices_size,
+                            device,
+                        );
+
+                        index_buffer.copy_from_slice(indices);
+                    }
+
+                    uniforms.push(TexturedUniforms::new(transform, mesh.tint));
+                    offsets.push((
+                        last_vertex as u64,
+                        last_index as u64,
+                        mesh.buffers.indices.len(),
+                        mesh.texture,
+                    ));
+
+                    last_vertex += mesh.buffers.vertices.len();
+                    last_index += mesh.buffers.indices.len();
+                }
+            }
+        }
+
+        let uniforms = uniforms.as_bytes();
+
+        if let Some(uniforms_size) =
+            wgpu::BufferSize::new(uniforms.len() as u64)
+        {
+            let mut uniforms_buffer = staging_belt.write_buffer(
+                encoder,
+                &self.textured_uniforms_buffer.raw,
+                0,
+                uniforms_size,
+                device,
+            );
+
+            uniforms_buffer.copy_from_slice(uniforms);
+        }
+
+        {
+            let (attachment, resolve_target, load) =
+                if let Some(blit) = &mut self.blit {
+                    let (attachment, resolve_target) =
+                        blit.targets(device, target_width, target_height);
+
+                    (attachment, Some(resolve_target), wgpu::LoadOp::Load)
+                } else {
+                    (target, None, wgpu::LoadOp::Load)
+                };
+
+            let mut render_pass =
+                encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+                    color_attachments: &[
+                        wgpu::RenderPassColorAttachmentDescriptor {
+                            attachment,
+                            resolve_target,
+                            ops: wgpu::Operations { load, store: true },
+                        },
+                    ],
+                    depth_stencil_attachment: None,
+                });
+
+            render_pass.set_pipeline(&self.textured_pipeline);
+            render_pass
+                .set_index_buffer(self.textured_index_buffer.raw.slice(..));
+            render_pass.set_vertex_buffer(
+                0,
+                self.textured_vertex_buffer.raw.slice(..),
+            );
+
+            // We deliberately draw in submission order rather than sorting
+            // or batching by texture: this pipeline has no depth test, so
+            // submission order is the only thing that keeps overlapping,
+            // alpha-blended textured meshes compositing the way the caller
+            // intended. The bind group's dynamic offset still changes per
+            // mesh, so there's nothing to gain from grouping by texture
+            // here anyway.
+            for (i, &(vertex_offset, index_offset, indices, texture)) in
+                offsets.iter().enumerate()
+            {
+                let bind_group = match self.textured_bind_groups.get(&texture) {
+                    Some(bind_group) => bind_group,
+                    None => continue,
+                };
+
+                let clip_bounds = (meshes[i].clip_bounds * scale_factor).snap();
+
+                render_pass.set_scissor_rect(
+                    clip_bounds.x,
+                    clip_bounds.y,
+                    clip_bounds.width,
+                    clip_bounds.height,
+                );
+
+                render_pass.set_bind_group(
+                    0,
+                    bind_group,
+                    &[(std::mem::size_of::<TexturedUniforms>() * i) as u32],
+                );
 
                 render_pass.draw_indexed(
                     index_offset as u32
@@ -408,28 +1725,126 @@ impl Pipeline {
 #[derive(Debug, Clone, Copy, AsBytes)]
 struct Uniforms {
     transform: [f32; 16],
+    // The depth to write into `gl_Position.z`, letting meshes be
+    // submitted in any order while still occluding correctly.
+    z: f32,
+    // std140 aligns a `vec4` to a 16-byte boundary, so the GLSL compiler
+    // inserts this padding after the scalar `u_Z` before `u_ColorMult`.
+    // We have to lay it out explicitly here too, or the two sides
+    // disagree on where every field after `z` lives.
+    _pad_z: [f32; 3],
+    // The color transform applied as `clamp(color * mult + add, 0, 1)`,
+    // reusing what used to be wasted padding.
+    color_mult: [f32; 4],
+    color_add: [f32; 4],
     // We need to align this to 256 bytes to please `wgpu`...
     // TODO: Be smarter and stop wasting memory!
-    _padding_a: [f32; 32],
-    _padding_b: [f32; 16],
+    _padding_a: [f32; 21],
+    _padding_b: [f32; 15],
+}
+
+impl Uniforms {
+    fn new(
+        transform: Transformation,
+        z: f32,
+        color_transform: ColorTransform,
+    ) -> Self {
+        Self {
+            transform: transform.into(),
+            z,
+            _pad_z: [0.0; 3],
+            color_mult: color_transform.mult,
+            color_add: color_transform.add,
+            _padding_a: [0.0; 21],
+            _padding_b: [0.0; 15],
+        }
+    }
 }
 
 impl Default for Uniforms {
     fn default() -> Self {
         Self {
             transform: *Transformation::identity().as_ref(),
-            _padding_a: [0.0; 32],
-            _padding_b: [0.0; 16],
+            z: 0.0,
+            _pad_z: [0.0; 3],
+            color_mult: ColorTransform::default().mult,
+            color_add: ColorTransform::default().add,
+            _padding_a: [0.0; 21],
+            _padding_b: [0.0; 15],
         }
     }
 }
 
 impl From<Transformation> for Uniforms {
     fn from(transformation: Transformation) -> Uniforms {
+        Uniforms::new(transformation, 0.0, ColorTransform::default())
+    }
+}
+
+#[repr(C)]
+#[derive(Debug, Clone, Copy, AsBytes)]
+struct GradientUniforms {
+    transform: [f32; 16],
+    gradient_transform: [f32; 16],
+    ratios: [f32; GRADIENT_MAX_STOPS],
+    colors: [[f32; 4]; GRADIENT_MAX_STOPS],
+    gradient_type: u32,
+    stop_count: u32,
+    spread: u32,
+    // Rounds the struct up to a multiple of 256 bytes, as `wgpu` requires
+    // for dynamic uniform buffer offsets.
+    _padding: [f32; 13],
+}
+
+impl GradientUniforms {
+    fn new(transform: Transformation, gradient: &Gradient) -> Self {
+        let mut ratios = [0.0; GRADIENT_MAX_STOPS];
+        let mut colors = [[0.0; 4]; GRADIENT_MAX_STOPS];
+
+        let stop_count = gradient.stops.len().min(GRADIENT_MAX_STOPS);
+
+        for (i, (ratio, color)) in
+            gradient.stops.iter().take(stop_count).enumerate()
+        {
+            ratios[i] = *ratio;
+            colors[i] = *color;
+        }
+
+        Self {
+            transform: transform.into(),
+            gradient_transform: gradient.transform.into(),
+            ratios,
+            colors,
+            gradient_type: match gradient.kind {
+                GradientKind::Linear => 0,
+                GradientKind::Radial => 1,
+            },
+            stop_count: stop_count as u32,
+            spread: match gradient.spread {
+                Spread::Pad => 0,
+                Spread::Reflect => 1,
+                Spread::Repeat => 2,
+            },
+            _padding: [0.0; 13],
+        }
+    }
+}
+
+#[repr(C)]
+#[derive(Debug, Clone, Copy, AsBytes)]
+struct TexturedUniforms {
+    transform: [f32; 16],
+    tint: [f32; 4],
+    // We need to align this to 256 bytes to please `wgpu`...
+    _padding: [f32; 44],
+}
+
+impl TexturedUniforms {
+    fn new(transform: Transformation, tint: [f32; 4]) -> Self {
         Self {
-            transform: transformation.into(),
-            _padding_a: [0.0; 32],
-            _padding_b: [0.0; 16],
+            transform: transform.into(),
+            tint,
+            _padding: [0.0; 44],
         }
     }
 }